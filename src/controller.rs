@@ -0,0 +1,76 @@
+use std::cell::Cell;
+
+/// A snapshot of which of the 8 standard NES controller buttons are
+/// currently held down, as handed to `Nes::step_frame` each frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControllerState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl ControllerState {
+    /// Pack into the bit order real NES controllers shift out: A, B,
+    /// Select, Start, Up, Down, Left, Right, A first.
+    fn to_byte(self) -> u8 {
+        (self.a as u8)
+            | (self.b as u8) << 1
+            | (self.select as u8) << 2
+            | (self.start as u8) << 3
+            | (self.up as u8) << 4
+            | (self.down as u8) << 5
+            | (self.left as u8) << 6
+            | (self.right as u8) << 7
+    }
+}
+
+/// One NES controller port's $4016/$4017 strobe-and-shift protocol.
+///
+/// Like `Cpu` and `Ppu`, this takes `&self` throughout and keeps its
+/// state in `Cell`s for the same reason: it only ever has a shared
+/// `&Nes` to work with.
+pub struct Controller {
+    /// The buttons actually held right now, latched in by `Nes` once
+    /// per frame from `step_frame`'s input.
+    live: Cell<u8>,
+    /// The byte being shifted out one bit per read.
+    shift: Cell<u8>,
+    strobe: Cell<bool>,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller { live: Cell::new(0), shift: Cell::new(0), strobe: Cell::new(false) }
+    }
+
+    pub fn set_input(&self, state: ControllerState) {
+        self.live.set(state.to_byte());
+    }
+
+    /// A write to $4016 sets the strobe bit for both controllers. While
+    /// it's set, every read reloads the shift register from the live
+    /// button state, so it always reports button A; the falling edge
+    /// latches whatever was held at that instant for `read` to shift out.
+    pub fn write_strobe(&self, value: u8) {
+        self.strobe.set(value & 0x01 != 0);
+        if self.strobe.get() {
+            self.shift.set(self.live.get());
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        if self.strobe.get() {
+            self.shift.set(self.live.get());
+        }
+
+        let bit = self.shift.get() & 0x01;
+        // Real hardware shifts in 1s once all 8 buttons are exhausted.
+        self.shift.set((self.shift.get() >> 1) | 0x80);
+        bit
+    }
+}
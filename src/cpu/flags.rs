@@ -0,0 +1,59 @@
+use super::Cpu;
+
+const CARRY: u8 = 1 << 0;
+const ZERO: u8 = 1 << 1;
+const INTERRUPT_DISABLE: u8 = 1 << 2;
+const DECIMAL: u8 = 1 << 3;
+const OVERFLOW: u8 = 1 << 6;
+const NEGATIVE: u8 = 1 << 7;
+
+impl Cpu {
+    fn set_flag(&self, flag: u8, set: bool) {
+        if set {
+            self.p.set(self.p.get() | flag);
+        } else {
+            self.p.set(self.p.get() & !flag);
+        }
+    }
+
+    pub fn carry(&self) -> bool { self.p.get() & CARRY != 0 }
+    pub fn set_carry(&self, set: bool) { self.set_flag(CARRY, set) }
+
+    pub fn zero(&self) -> bool { self.p.get() & ZERO != 0 }
+    pub fn set_zero(&self, set: bool) { self.set_flag(ZERO, set) }
+
+    pub fn interrupt_disable(&self) -> bool { self.p.get() & INTERRUPT_DISABLE != 0 }
+    pub fn set_interrupt_disable(&self, set: bool) { self.set_flag(INTERRUPT_DISABLE, set) }
+
+    pub fn decimal(&self) -> bool { self.p.get() & DECIMAL != 0 }
+    pub fn set_decimal(&self, set: bool) { self.set_flag(DECIMAL, set) }
+
+    pub fn overflow(&self) -> bool { self.p.get() & OVERFLOW != 0 }
+    pub fn set_overflow(&self, set: bool) { self.set_flag(OVERFLOW, set) }
+
+    pub fn negative(&self) -> bool { self.p.get() & NEGATIVE != 0 }
+    pub fn set_negative(&self, set: bool) { self.set_flag(NEGATIVE, set) }
+
+    /// Set Zero and Negative from a result value, the way every load,
+    /// transfer, increment and logic op on the 6502 does.
+    pub fn set_zn(&self, result: u8) {
+        self.set_zero(result == 0);
+        self.set_negative(result & 0x80 != 0);
+    }
+
+    /// `A = A + value + Carry`, with Carry/Overflow/Zero/Negative all
+    /// set from the result. SBC is implemented in terms of this: since
+    /// `A - M - (1 - C)` is the same arithmetic as `A + !M + C`,
+    /// `Sbc` just calls `adc(!value)`.
+    pub fn adc(&self, value: u8) {
+        let a = self.a.get();
+        let carry_in = self.carry() as u16;
+        let sum = a as u16 + value as u16 + carry_in;
+        let result = sum as u8;
+
+        self.set_carry(sum > 0xFF);
+        self.set_overflow((a ^ result) & (value ^ result) & 0x80 != 0);
+        self.a.set(result);
+        self.set_zn(result);
+    }
+}
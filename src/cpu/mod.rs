@@ -0,0 +1,21 @@
+mod decode;
+mod flags;
+
+pub use decode::{base_cycles, branch_extra_cycles, decode, AddressMode, Instr, Operation};
+
+use std::cell::Cell;
+
+#[derive(Debug)]
+pub struct Cpu {
+    pub pc: Cell<u16>,
+    pub a: Cell<u8>,
+    pub x: Cell<u8>,
+    pub y: Cell<u8>,
+    pub s: Cell<u8>,
+    pub p: Cell<u8>,
+    pub nmi: Cell<bool>,
+    /// Total CPU cycles elapsed since power-on, for `Nes::trace`'s `CYC:`
+    /// field and for test-ROM harnesses that check timing against a
+    /// golden log.
+    pub cyc: Cell<u64>,
+}
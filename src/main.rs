@@ -1,41 +1,183 @@
 #![feature(generators, generator_trait)]
 
-use std::fs;
-use std::io::Read;
+mod controller;
+mod cpu;
+mod mapper;
+mod ppu;
+mod rom;
+
 use std::pin::Pin;
 use std::ops::{Generator, GeneratorState};
-use std::cell::Cell;
+use std::cell::{Cell, Ref, RefCell};
+use std::marker::PhantomPinned;
+
+use controller::{Controller, ControllerState};
+use cpu::{AddressMode, Cpu, Operation};
+use mapper::Mapper;
+use ppu::Ppu;
+use rom::{Mirroring, RomImage};
+
+/// Shared by the Accumulator and memory forms of ASL/LSR/ROL/ROR:
+/// shift or rotate `value` by one bit, returning `(result, carry_out)`.
+fn shift_or_rotate(op: Operation, value: u8, carry_in: bool) -> (u8, bool) {
+    match op {
+        Operation::Asl => (value << 1, value & 0x80 != 0),
+        Operation::Lsr => (value >> 1, value & 0x01 != 0),
+        Operation::Rol => ((value << 1) | carry_in as u8, value & 0x80 != 0),
+        Operation::Ror => ((value >> 1) | ((carry_in as u8) << 7), value & 0x01 != 0),
+        _ => unreachable!(),
+    }
+}
+
+/// One rendered frame's worth of output, handed back by `Nes::step_frame`.
+struct Frame {
+    video: Box<[u32; ppu::WIDTH * ppu::HEIGHT]>,
+    audio: Vec<f32>,
+}
 
+/// The boxed, pinned generator backing `Nes::frame_generator`.
+type FrameGenerator = Pin<Box<dyn Generator<Yield = (), Return = ()>>>;
+
+/// Holds the CPU/PPU state a running frame's generator (see
+/// `step_frame`) ends up pointing back into. That self-reference is
+/// only sound as long as a `Nes` never moves once `step_frame` has run,
+/// so `Nes` is `!Unpin` (via `_pin`) and `step_frame` only accepts a
+/// `Pin<&Nes>` - the only safe way to get one is from storage (e.g.
+/// `Box::pin`) that's already committed to never moving or freeing it
+/// while anything still borrows it.
 struct Nes {
     cpu: Cpu,
     ram: Cell<[u8; 0x0800]>, // Famicom only has 2KiB of built-in RAM
-    rom: Rom,
-    // TODO: PPU
+    // Cartridge-side RAM at $6000-$7FFF. Most mappers don't have any,
+    // but test ROMs in the nestest/blargg family use it unconditionally
+    // to report pass/fail status ($6000) and a human-readable message
+    // ($6004 onward), so it lives here rather than behind `Mapper`.
+    prg_ram: Cell<[u8; 0x2000]>,
+    mapper: Box<dyn Mapper>,
+    mirroring: Mirroring,
+    ppu: Ppu,
+    controller1: Controller,
+    controller2: Controller,
+    // The generator driving `step_frame` across calls, behind a
+    // `RefCell` for the same reason `Ppu::framebuffer` is - `step_frame`
+    // only ever has a `Pin<&Nes>` to work with.
+    frame_generator: RefCell<Option<FrameGenerator>>,
+    _pin: PhantomPinned,
     // TODO: APU
 }
 
 impl Nes {
-    fn from_rom(rom: Rom) -> Self {
-        // Convert the 2 bytes at offset 0x3FFC / 0x3FFD
-        // to a u16 to get PC
-        // NOTE: This only works for NROM ROMs with
-        // a size of 16 KiB!        
-        let pc_bytes = &rom.prg_rom[0x3FFC..=0x3FFD];
-        let pc = (pc_bytes[0] as u16) | ((pc_bytes[1] as u16) << 8);
-
-        // See http://wiki.nesdev.com/w/index.php/CPU_power_up_state        
-        let cpu = Cpu { 
+    fn from_rom(rom: RomImage) -> Self {
+        let mirroring = rom.mirroring;
+        let mapper = mapper::from_rom_image(rom);
+
+        // Convert the 2 bytes at $FFFC/$FFFD to a u16 to get PC.
+        // See http://wiki.nesdev.com/w/index.php/CPU_power_up_state
+        let pc = (mapper.cpu_read(0xFFFC) as u16)
+            | ((mapper.cpu_read(0xFFFD) as u16) << 8);
+
+        let cpu = Cpu {
             pc: Cell::new(pc),
-            a: Cell::new(0), 
-            x: Cell::new(0), 
-            y: Cell::new(0), 
-            s: Cell::new(0xFD), 
-            p: Cell::new(0x34), 
+            a: Cell::new(0),
+            x: Cell::new(0),
+            y: Cell::new(0),
+            s: Cell::new(0xFD),
+            p: Cell::new(0x34),
             nmi: Cell::new(false),
+            cyc: Cell::new(0),
         };
         let ram = Cell::new([0; 0x0800]);
 
-        Nes { cpu, ram, rom }
+        Nes {
+            cpu,
+            ram,
+            prg_ram: Cell::new([0; 0x2000]),
+            mapper,
+            mirroring,
+            ppu: Ppu::new(),
+            controller1: Controller::new(),
+            controller2: Controller::new(),
+            frame_generator: RefCell::new(None),
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Run the CPU and PPU until one full frame (one VBlank) has been
+    /// produced, latching `input` into the controller shift registers
+    /// as the frame's live button state.
+    ///
+    /// Takes `Pin<&Nes>` rather than `&mut self` (or even `&self`)
+    /// because the generator this drives borrows `self` for as long as
+    /// it keeps getting resumed, which is every `step_frame` call for
+    /// the rest of this `Nes`'s life - longer than the borrow checker
+    /// can see from inside a single method. Requiring the caller to
+    /// already hold a `Pin<&Nes>` (e.g. by keeping the `Nes` in a
+    /// `Pin<Box<Nes>>` from the start) is what makes erasing that
+    /// borrow's lifetime below actually sound, instead of merely
+    /// documented as a caller obligation.
+    fn step_frame(self: Pin<&Self>, input: [ControllerState; 2]) -> Frame {
+        let this = self.get_ref();
+        this.controller1.set_input(input[0]);
+        this.controller2.set_input(input[1]);
+
+        if this.frame_generator.borrow().is_none() {
+            let generator = this.run();
+
+            // SAFETY: `this` came from a `Pin<&Nes>`, and `Nes` is
+            // `!Unpin`, so `this` is guaranteed to stay at this address
+            // for as long as anything (including the generator we're
+            // about to store) might still reference it. That's exactly
+            // the guarantee erasing this borrow to `'static` needs.
+            let boxed: Box<dyn Generator<Yield = (), Return = ()> + '_> = Box::new(generator);
+            let boxed: Box<dyn Generator<Yield = (), Return = ()>> =
+                unsafe { std::mem::transmute(boxed) };
+            *this.frame_generator.borrow_mut() = Some(Box::into_pin(boxed));
+        }
+
+        let mut generator = this.frame_generator.borrow_mut();
+        loop {
+            match generator.as_mut().unwrap().as_mut().resume() {
+                GeneratorState::Yielded(()) => {
+                    if this.ppu.frame_ready.replace(false) {
+                        break;
+                    }
+                }
+                GeneratorState::Complete(()) => break,
+            }
+        }
+        drop(generator);
+
+        Frame { video: Box::new(*this.framebuffer()), audio: Vec::new() }
+    }
+
+    /// The current frame's pixels, row-major, top-left first, as packed
+    /// 24-bit RGB. Borrowed rather than copied, since a frontend will
+    /// typically want to read this once per frame rather than every
+    /// cycle.
+    fn framebuffer(&self) -> Ref<[u32; ppu::WIDTH * ppu::HEIGHT]> {
+        self.ppu.framebuffer.borrow()
+    }
+
+    /// A single-line trace of CPU state at the current instruction
+    /// boundary, in `PC opcode A:xx X:xx Y:xx P:xx SP:xx CYC:n` form.
+    /// Meant to be called once per instruction and diffed against a
+    /// golden log, the way `nestest`-style test ROMs are normally
+    /// validated.
+    fn trace(&self) -> String {
+        let pc = self.cpu.pc.get();
+        let opcode = self.read_u8(pc);
+
+        format!(
+            "{:04X} {:02X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            pc,
+            opcode,
+            self.cpu.a.get(),
+            self.cpu.x.get(),
+            self.cpu.y.get(),
+            self.cpu.p.get(),
+            self.cpu.s.get(),
+            self.cpu.cyc.get(),
+        )
     }
 
     fn read_u8(&self, addr: u16) -> u8 {
@@ -47,27 +189,24 @@ impl Nes {
                 let ram_offset = (addr as usize) % ram.len();
                 ram[ram_offset].get()
             }
-            // PRG-ROM (mirrored to fill all 32 KiB)
-            0x8000..=0xFFFF => {
-                let rom_len = self.rom.prg_rom.len();
-                let rom_offset = (addr as usize - 0x8000) % rom_len;
-                self.rom.prg_rom[rom_offset]
+            // PPU registers (mirrored every 8 bytes)
+            0x2000..=0x3FFF => self.ppu_register_read(0x2000 + (addr - 0x2000) % 8),
+            // Controller ports
+            0x4016 => self.controller1.read(),
+            0x4017 => self.controller2.read(),
+            // Cartridge RAM
+            0x6000..=0x7FFF => {
+                let prg_ram: &Cell<[u8]> = &self.prg_ram;
+                prg_ram.as_slice_of_cells()[(addr - 0x6000) as usize].get()
             }
+            // PRG-ROM/RAM, handled by the cartridge mapper
+            0x8000..=0xFFFF => self.mapper.cpu_read(addr),
             _ => {
                 unimplemented!("Read from ${:04X}", addr);
             }
         }
     }
 
-    // This is the same logic we used in `Nes::from_rom`, so
-    // we could refactor this
-    fn read_u16(&self, addr: u16) -> u16 {
-        let lo = self.read_u8(addr);
-        let hi = self.read_u8(addr.wrapping_add(1));
-
-        (lo as u16) | ((hi as u16) << 8)
-    }
-
     fn write_u8(&self, addr: u16, value: u8) {
         match addr {
             // RAM (mirrored every 0x0800 bytes)
@@ -77,120 +216,607 @@ impl Nes {
                 let ram_offset = (addr as usize) % ram.len();
                 ram[ram_offset].set(value);
             }
-            // PRG-ROM (writes are ignored for NROM)
-            0x8000..=0xFFFF => { }
+            // PPU registers (mirrored every 8 bytes)
+            0x2000..=0x3FFF => self.ppu_register_write(0x2000 + (addr - 0x2000) % 8, value),
+            // $4016 strobes both controller ports at once; $4017 has no
+            // write side of its own here (that's the APU frame counter
+            // on real hardware).
+            0x4016 => {
+                self.controller1.write_strobe(value);
+                self.controller2.write_strobe(value);
+            }
+            // Cartridge RAM
+            0x6000..=0x7FFF => {
+                let prg_ram: &Cell<[u8]> = &self.prg_ram;
+                prg_ram.as_slice_of_cells()[(addr - 0x6000) as usize].set(value);
+            }
+            // PRG-ROM/RAM, handled by the cartridge mapper
+            0x8000..=0xFFFF => self.mapper.cpu_write(addr, value),
             _ => {
                 unimplemented!("Write to ${:04X}", addr);
             }
         }
     }
 
+    /// Handle a CPU read from one of the 8 PPU ports, already folded
+    /// down from its mirrors to `$2000-$2007`.
+    fn ppu_register_read(&self, reg: u16) -> u8 {
+        match reg {
+            0x2002 => {
+                let status = self.ppu.status.get();
+                self.ppu.status.set(status & !ppu::STATUS_VBLANK);
+                self.ppu.w.set(false);
+                status
+            }
+            0x2004 => self.ppu.oam.get()[self.ppu.oam_addr.get() as usize],
+            0x2007 => {
+                let addr = self.ppu.v.get() & 0x3FFF;
+                let value = if addr >= 0x3F00 {
+                    // Palette reads aren't delayed by the read buffer.
+                    self.ppu_bus_read(addr)
+                } else {
+                    let buffered = self.ppu.read_buffer.get();
+                    self.ppu.read_buffer.set(self.ppu_bus_read(addr));
+                    buffered
+                };
+                self.increment_vram_address();
+                value
+            }
+            // $2000/$2001/$2003/$2005/$2006 are write-only.
+            _ => 0,
+        }
+    }
+
+    /// Handle a CPU write to one of the 8 PPU ports, already folded
+    /// down from its mirrors to `$2000-$2007`.
+    fn ppu_register_write(&self, reg: u16, value: u8) {
+        match reg {
+            0x2000 => {
+                let was_nmi_enabled = self.ppu.ctrl.get() & ppu::CTRL_NMI_ENABLE != 0;
+                self.ppu.ctrl.set(value);
+                // t: ...BA.. ........ = d1d0 (nametable select)
+                let t = self.ppu.t.get();
+                self.ppu.t.set((t & !0x0C00) | ((value as u16 & 0x03) << 10));
+
+                // Toggling bit 7 from 0 to 1 while VBlank is still set
+                // (i.e. we're between VBlank start and its clear on the
+                // pre-render line) re-triggers NMI immediately, rather
+                // than waiting for the next VBlank-start event.
+                let nmi_enabled = value & ppu::CTRL_NMI_ENABLE != 0;
+                if !was_nmi_enabled && nmi_enabled && self.ppu.status.get() & ppu::STATUS_VBLANK != 0 {
+                    self.cpu.nmi.set(true);
+                }
+            }
+            0x2001 => self.ppu.mask.set(value),
+            0x2003 => self.ppu.oam_addr.set(value),
+            0x2004 => {
+                let mut oam = self.ppu.oam.get();
+                oam[self.ppu.oam_addr.get() as usize] = value;
+                self.ppu.oam.set(oam);
+                self.ppu.oam_addr.set(self.ppu.oam_addr.get().wrapping_add(1));
+            }
+            0x2005 => {
+                if !self.ppu.w.get() {
+                    // First write: fine X and coarse X.
+                    self.ppu.x.set(value & 0x07);
+                    let t = self.ppu.t.get();
+                    self.ppu.t.set((t & !0x001F) | (value as u16 >> 3));
+                } else {
+                    // Second write: fine Y and coarse Y.
+                    let t = self.ppu.t.get();
+                    let coarse_y = (value as u16 >> 3) & 0x1F;
+                    let fine_y = (value as u16 & 0x07) << 12;
+                    self.ppu.t.set((t & !0x73E0) | (coarse_y << 5) | fine_y);
+                }
+                self.ppu.w.set(!self.ppu.w.get());
+            }
+            0x2006 => {
+                if !self.ppu.w.get() {
+                    // First write: high 6 bits of the address.
+                    let t = self.ppu.t.get();
+                    self.ppu.t.set((t & 0x00FF) | ((value as u16 & 0x3F) << 8));
+                } else {
+                    // Second write: low 8 bits, and `v` is latched from `t`.
+                    let t = (self.ppu.t.get() & 0xFF00) | value as u16;
+                    self.ppu.t.set(t);
+                    self.ppu.v.set(t);
+                }
+                self.ppu.w.set(!self.ppu.w.get());
+            }
+            0x2007 => {
+                let addr = self.ppu.v.get() & 0x3FFF;
+                self.ppu_bus_write(addr, value);
+                self.increment_vram_address();
+            }
+            // $2002/$2004 read port handled above; writes to it go through $2004.
+            _ => {}
+        }
+    }
+
+    fn increment_vram_address(&self) {
+        // Ctrl bit 2 selects a +1 ("going across") or +32 ("going down")
+        // step, so that a full-screen PPUDATA fill can walk a nametable
+        // either by row or by column.
+        let increment = if self.ppu.ctrl.get() & 0x04 != 0 {
+            ppu::VRAM_INCREMENT_ACROSS as u16
+        } else {
+            ppu::VRAM_INCREMENT_DOWN as u16
+        };
+        self.ppu.v.set(self.ppu.v.get().wrapping_add(increment));
+    }
+
+    /// Read the PPU's own address space (`$0000-$3FFF`): CHR from the
+    /// cartridge mapper, nametables (mirrored per the cartridge's
+    /// wiring), and palette RAM.
+    fn ppu_bus_read(&self, addr: u16) -> u8 {
+        match addr & 0x3FFF {
+            0x0000..=0x1FFF => self.mapper.ppu_read(addr),
+            0x2000..=0x3EFF => {
+                let vram: &Cell<[u8]> = &self.ppu.vram;
+                vram.as_slice_of_cells()[ppu::nametable_index(addr, self.mirroring)].get()
+            }
+            0x3F00..=0x3FFF => {
+                let palette: &Cell<[u8]> = &self.ppu.palette;
+                palette.as_slice_of_cells()[ppu::palette_index(addr)].get()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn ppu_bus_write(&self, addr: u16, value: u8) {
+        match addr & 0x3FFF {
+            0x0000..=0x1FFF => self.mapper.ppu_write(addr, value),
+            0x2000..=0x3EFF => {
+                let vram: &Cell<[u8]> = &self.ppu.vram;
+                vram.as_slice_of_cells()[ppu::nametable_index(addr, self.mirroring)].set(value);
+            }
+            0x3F00..=0x3FFF => {
+                let palette: &Cell<[u8]> = &self.ppu.palette;
+                palette.as_slice_of_cells()[ppu::palette_index(addr)].set(value);
+            }
+            _ => unreachable!(),
+        }
+    }
+
     fn run_cpu<'a>(&'a self)
         -> impl Generator<Yield = (), Return = ()> + 'a
     {
         move || loop {
             if self.cpu.nmi.get() {
-                // NOTE: It's intentional that this
-                // check happens on the cycle before
-                // the next instruction executes!
-
-                // TODO: Read NMI interrupt vector
-                // from address $FFFE, then set PC
-                println!("=== NMI! ===");
+                // NOTE: It's intentional that this check happens on the
+                // cycle before the next instruction executes: the NMI
+                // line is latched into `cpu.nmi` as soon as the PPU
+                // asserts it (see `run_ppu`) and is only polled here, at
+                // instruction boundaries, so a request that arrives
+                // mid-instruction is never dropped - it just waits for
+                // the current instruction to finish.
                 self.cpu.nmi.set(false);
+
+                // The 7-cycle NMI sequence: two internal/dummy cycles,
+                // push PCH, PCL, then P (with the B flag clear, since
+                // this is a hardware interrupt rather than BRK/PHP), set
+                // the interrupt-disable flag, and load PC from the NMI
+                // vector at $FFFA/$FFFB. Compare `Operation::Brk` below,
+                // which gets its second dummy cycle from the opcode-fetch
+                // dummy read instead of a bare `yield` here.
+                yield; // internal operation
+                yield; // internal operation
+                self.write_u8(0x0100 + self.cpu.s.get() as u16, (self.cpu.pc.get() >> 8) as u8);
+                self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                yield;
+                self.write_u8(0x0100 + self.cpu.s.get() as u16, self.cpu.pc.get() as u8);
+                self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                yield;
+                self.write_u8(0x0100 + self.cpu.s.get() as u16, (self.cpu.p.get() & !0x10) | 0x20);
+                self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                yield;
+                self.cpu.set_interrupt_disable(true);
+                let lo = self.read_u8(0xFFFA);
+                yield;
+                let hi = self.read_u8(0xFFFB);
+                self.cpu.pc.set(u16::from_le_bytes([lo, hi]));
+                yield;
+
+                continue;
             }
 
             let opcode = self.read_u8(self.cpu.pc.get());
             self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
             yield;
 
-            match opcode {
-                // LDA:
-                //   Load immediate value into A
-                0xA9 => {
-                    let value = self.read_u8(self.cpu.pc.get());
+            let instr = cpu::decode(opcode);
+
+            // --- Operand fetch ---
+            //
+            // Turn the instruction stream into either an immediate
+            // `value`, an effective `addr`, or neither (Implied /
+            // Accumulator), yielding once per bus cycle it actually
+            // takes on real hardware. `page_crossed` is only set by
+            // the indexed modes, and only read-type instructions pay
+            // an extra cycle for it below; writes and read-modify-write
+            // ops always pay the worst case, since the CPU issues the
+            // same bus cycles either way.
+            let mut value: Option<u8> = None;
+            let mut addr: Option<u16> = None;
+            let mut page_crossed = false;
+
+            match instr.mode {
+                AddressMode::Implied | AddressMode::Accumulator => {
+                    // Dummy read of the next opcode byte; the value is discarded.
+                    let _ = self.read_u8(self.cpu.pc.get());
+                    yield;
+                }
+                AddressMode::Immediate | AddressMode::Relative => {
+                    let v = self.read_u8(self.cpu.pc.get());
                     self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
-                    self.cpu.a.set(value);
                     yield;
+                    value = Some(v);
                 }
-                // ADC:
-                //   Add immediate value to A
-                0x69 => {
-                    let value = self.read_u8(self.cpu.pc.get());
+                AddressMode::ZeroPage => {
+                    let a = self.read_u8(self.cpu.pc.get()) as u16;
                     self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
-                    self.cpu.a.set(self.cpu.a.get().wrapping_add(value));
                     yield;
+                    addr = Some(a);
                 }
-                // TAX:
-                //   Transfer A to X
-                0xAA => {
-                    let _garbage = self.read_u8(self.cpu.pc.get());
-                    self.cpu.x.set(self.cpu.a.get());
+                AddressMode::ZeroPageX | AddressMode::ZeroPageY => {
+                    let base = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
+                    yield;
+                    // Dummy read at the unindexed address before the index is added.
+                    let _ = self.read_u8(base as u16);
                     yield;
+                    let index = if instr.mode == AddressMode::ZeroPageX {
+                        self.cpu.x.get()
+                    } else {
+                        self.cpu.y.get()
+                    };
+                    addr = Some(base.wrapping_add(index) as u16);
                 }
-                // STX:
-                //   Store X to address between
-                //   $0000 and $00FF
-                0x86 => {
-                    // Cycle 2
-                    let addr_lo =
-                        self.read_u8(self.cpu.pc.get());
-                    // Between $0000 and $00FFF:
-                    let addr = addr_lo as u16;
+                AddressMode::Absolute => {
+                    let lo = self.read_u8(self.cpu.pc.get());
                     self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
                     yield;
-
-                    // Cycle 3
-                    self.write_u8(addr, self.cpu.x.get());
+                    let hi = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
                     yield;
+                    addr = Some(u16::from_le_bytes([lo, hi]));
                 }
-                // LDA:
-                //   Load A from address between
-                //   $0000 and $00FF
-                0xA5 => {
-                    // cycle 2 (read)
-                    let addr_lo = self.read_u8(self.cpu.pc.get());
-                    // Between $0000 and $00FF:
-                    let addr = addr_lo as u16;
+                AddressMode::AbsoluteX | AddressMode::AbsoluteY => {
+                    let lo = self.read_u8(self.cpu.pc.get());
                     self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
                     yield;
-                    // cycle 3 (modify)
-                    let value = self.read_u8(addr);
-                    self.cpu.a.set(value);
+                    let hi = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
                     yield;
+                    let base = u16::from_le_bytes([lo, hi]);
+                    let index = if instr.mode == AddressMode::AbsoluteX {
+                        self.cpu.x.get()
+                    } else {
+                        self.cpu.y.get()
+                    };
+                    let target = base.wrapping_add(index as u16);
+                    page_crossed = (target & 0xFF00) != (base & 0xFF00);
+                    addr = Some(target);
                 }
-                // JMP:
-                //   Jump to address by changing PC
-                0x4C => {
-                    // Cycle 2:
-                    //   Read the low address of the jump
-                    //   target by reading PC, then increment PC
-                    let target_lo = self.read_u8(self.cpu.pc.get());
+                AddressMode::Indirect => {
+                    let lo = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
+                    yield;
+                    let hi = self.read_u8(self.cpu.pc.get());
                     self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
                     yield;
-                    
-                    // Cycle 3:
-                    //   Read the high address of the jump
-                    //   target and set PC
-                    let target_hi = self.read_u8(self.cpu.pc.get());
-                    let target =
-                        (target_lo as u16)
-                        | ((target_hi as u16) << 8);
-                    self.cpu.pc.set(target);
+                    let ptr = u16::from_le_bytes([lo, hi]);
+                    // Famous 6502 bug: if the pointer sits on a page
+                    // boundary (e.g. $xxFF), the high byte wraps within
+                    // the same page instead of crossing into the next.
+                    let ptr_next = (ptr & 0xFF00) | (ptr as u8).wrapping_add(1) as u16;
+                    let target_lo = self.read_u8(ptr);
+                    yield;
+                    let target_hi = self.read_u8(ptr_next);
                     yield;
+                    addr = Some(u16::from_le_bytes([target_lo, target_hi]));
                 }
-                _ => {
-                    unimplemented!("Opcode {:02X}", opcode);
+                AddressMode::IndirectX => {
+                    let base = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
+                    yield;
+                    let _ = self.read_u8(base as u16);
+                    yield;
+                    let ptr = base.wrapping_add(self.cpu.x.get());
+                    let lo = self.read_u8(ptr as u16);
+                    yield;
+                    let hi = self.read_u8(ptr.wrapping_add(1) as u16);
+                    yield;
+                    addr = Some(u16::from_le_bytes([lo, hi]));
+                }
+                AddressMode::IndirectY => {
+                    let ptr = self.read_u8(self.cpu.pc.get());
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
+                    yield;
+                    let lo = self.read_u8(ptr as u16);
+                    yield;
+                    let hi = self.read_u8(ptr.wrapping_add(1) as u16);
+                    yield;
+                    let base = u16::from_le_bytes([lo, hi]);
+                    let target = base.wrapping_add(self.cpu.y.get() as u16);
+                    page_crossed = (target & 0xFF00) != (base & 0xFF00);
+                    addr = Some(target);
                 }
             }
 
-            // Some nice debug output so we can see
-            // the CPU state after every cycle
-            println!("Opcode: {:02X}", opcode);
-            println!("CPU State: {:02X?}", self.cpu);
-            println!("-----------------");
+            // --- Execute ---
+            match instr.operation {
+                Operation::Lda | Operation::Ldx | Operation::Ldy
+                | Operation::Adc | Operation::Sbc | Operation::And | Operation::Ora
+                | Operation::Eor | Operation::Cmp | Operation::Cpx | Operation::Cpy
+                | Operation::Bit => {
+                    let operand = match value {
+                        Some(v) => v,
+                        None => {
+                            if page_crossed {
+                                yield;
+                            }
+                            let v = self.read_u8(addr.unwrap());
+                            yield;
+                            v
+                        }
+                    };
+
+                    match instr.operation {
+                        Operation::Lda => { self.cpu.a.set(operand); self.cpu.set_zn(operand); }
+                        Operation::Ldx => { self.cpu.x.set(operand); self.cpu.set_zn(operand); }
+                        Operation::Ldy => { self.cpu.y.set(operand); self.cpu.set_zn(operand); }
+                        Operation::Adc => self.cpu.adc(operand),
+                        // A - M - (1 - C) is the same arithmetic as A + !M + C.
+                        Operation::Sbc => self.cpu.adc(!operand),
+                        Operation::And => {
+                            let result = self.cpu.a.get() & operand;
+                            self.cpu.a.set(result);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Ora => {
+                            let result = self.cpu.a.get() | operand;
+                            self.cpu.a.set(result);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Eor => {
+                            let result = self.cpu.a.get() ^ operand;
+                            self.cpu.a.set(result);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Cmp => {
+                            let result = self.cpu.a.get().wrapping_sub(operand);
+                            self.cpu.set_carry(self.cpu.a.get() >= operand);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Cpx => {
+                            let result = self.cpu.x.get().wrapping_sub(operand);
+                            self.cpu.set_carry(self.cpu.x.get() >= operand);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Cpy => {
+                            let result = self.cpu.y.get().wrapping_sub(operand);
+                            self.cpu.set_carry(self.cpu.y.get() >= operand);
+                            self.cpu.set_zn(result);
+                        }
+                        Operation::Bit => {
+                            self.cpu.set_zero(self.cpu.a.get() & operand == 0);
+                            self.cpu.set_overflow(operand & 0x40 != 0);
+                            self.cpu.set_negative(operand & 0x80 != 0);
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+
+                Operation::Sta | Operation::Stx | Operation::Sty => {
+                    // Indexed modes always issue a (discarded) read at
+                    // the wrong address before the real write, whether
+                    // or not the index actually crossed a page.
+                    if matches!(
+                        instr.mode,
+                        AddressMode::AbsoluteX | AddressMode::AbsoluteY | AddressMode::IndirectY
+                    ) {
+                        yield;
+                    }
+                    let v = match instr.operation {
+                        Operation::Sta => self.cpu.a.get(),
+                        Operation::Stx => self.cpu.x.get(),
+                        Operation::Sty => self.cpu.y.get(),
+                        _ => unreachable!(),
+                    };
+                    self.write_u8(addr.unwrap(), v);
+                    yield;
+                }
+
+                Operation::Tax => { self.cpu.x.set(self.cpu.a.get()); self.cpu.set_zn(self.cpu.x.get()); }
+                Operation::Tay => { self.cpu.y.set(self.cpu.a.get()); self.cpu.set_zn(self.cpu.y.get()); }
+                Operation::Txa => { self.cpu.a.set(self.cpu.x.get()); self.cpu.set_zn(self.cpu.a.get()); }
+                Operation::Tya => { self.cpu.a.set(self.cpu.y.get()); self.cpu.set_zn(self.cpu.a.get()); }
+                Operation::Tsx => { self.cpu.x.set(self.cpu.s.get()); self.cpu.set_zn(self.cpu.x.get()); }
+                // TXS doesn't touch any flags: S isn't a general-purpose register.
+                Operation::Txs => self.cpu.s.set(self.cpu.x.get()),
+
+                Operation::Inx => {
+                    self.cpu.x.set(self.cpu.x.get().wrapping_add(1));
+                    self.cpu.set_zn(self.cpu.x.get());
+                }
+                Operation::Iny => {
+                    self.cpu.y.set(self.cpu.y.get().wrapping_add(1));
+                    self.cpu.set_zn(self.cpu.y.get());
+                }
+                Operation::Dex => {
+                    self.cpu.x.set(self.cpu.x.get().wrapping_sub(1));
+                    self.cpu.set_zn(self.cpu.x.get());
+                }
+                Operation::Dey => {
+                    self.cpu.y.set(self.cpu.y.get().wrapping_sub(1));
+                    self.cpu.set_zn(self.cpu.y.get());
+                }
+
+                Operation::Asl | Operation::Lsr | Operation::Rol | Operation::Ror
+                | Operation::Inc | Operation::Dec => {
+                    if instr.mode == AddressMode::Accumulator {
+                        let v = self.cpu.a.get();
+                        let (result, carry_out) = shift_or_rotate(instr.operation, v, self.cpu.carry());
+                        self.cpu.a.set(result);
+                        self.cpu.set_carry(carry_out);
+                        self.cpu.set_zn(result);
+                    } else {
+                        // Abs,X always pays the extra cycle; it has no
+                        // page-crossing fast path for read-modify-write.
+                        if instr.mode == AddressMode::AbsoluteX {
+                            yield;
+                        }
+                        let a = addr.unwrap();
+                        let v = self.read_u8(a);
+                        yield;
+                        self.write_u8(a, v); // dummy write-back of the old value
+                        yield;
+                        let result = match instr.operation {
+                            Operation::Inc => v.wrapping_add(1),
+                            Operation::Dec => v.wrapping_sub(1),
+                            _ => {
+                                let (result, carry_out) = shift_or_rotate(instr.operation, v, self.cpu.carry());
+                                self.cpu.set_carry(carry_out);
+                                result
+                            }
+                        };
+                        self.write_u8(a, result);
+                        self.cpu.set_zn(result);
+                        yield;
+                    }
+                }
+
+                Operation::Pha | Operation::Php => {
+                    let v = match instr.operation {
+                        Operation::Pha => self.cpu.a.get(),
+                        // Bits 4 and 5 are always set when P is pushed by an instruction.
+                        Operation::Php => self.cpu.p.get() | 0x30,
+                        _ => unreachable!(),
+                    };
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, v);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                }
+                Operation::Pla | Operation::Plp => {
+                    yield; // dummy read while S is incremented
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    let v = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    match instr.operation {
+                        Operation::Pla => {
+                            self.cpu.a.set(v);
+                            self.cpu.set_zn(v);
+                        }
+                        Operation::Plp => self.cpu.p.set(v),
+                        _ => unreachable!(),
+                    }
+                    yield;
+                }
+
+                Operation::Jmp => {
+                    self.cpu.pc.set(addr.unwrap());
+                }
+                Operation::Jsr => {
+                    // JSR interleaves a push between fetching the low
+                    // and high address bytes, so it doesn't reuse the
+                    // generic Absolute-mode timing above.
+                    let return_addr = self.cpu.pc.get().wrapping_sub(1);
+                    yield; // internal operation
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, (return_addr >> 8) as u8);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, return_addr as u8);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                    self.cpu.pc.set(addr.unwrap());
+                }
+                Operation::Rts => {
+                    yield; // dummy read, discarded
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    yield;
+                    let lo = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    yield;
+                    let hi = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    yield;
+                    let ret = u16::from_le_bytes([lo, hi]);
+                    self.cpu.pc.set(ret.wrapping_add(1));
+                    yield;
+                }
+                Operation::Rti => {
+                    yield; // internal: increment S
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    let p = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    self.cpu.p.set(p);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    yield;
+                    let lo = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_add(1));
+                    yield;
+                    let hi = self.read_u8(0x0100 + self.cpu.s.get() as u16);
+                    self.cpu.pc.set(u16::from_le_bytes([lo, hi]));
+                    yield;
+                }
+                Operation::Brk => {
+                    // The byte after BRK is a padding byte that's part
+                    // of the instruction, but its value is never used.
+                    self.cpu.pc.set(self.cpu.pc.get().wrapping_add(1));
+                    let return_addr = self.cpu.pc.get();
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, (return_addr >> 8) as u8);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, return_addr as u8);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                    self.write_u8(0x0100 + self.cpu.s.get() as u16, self.cpu.p.get() | 0x30);
+                    self.cpu.s.set(self.cpu.s.get().wrapping_sub(1));
+                    yield;
+                    self.cpu.set_interrupt_disable(true);
+                    let lo = self.read_u8(0xFFFE);
+                    yield;
+                    let hi = self.read_u8(0xFFFF);
+                    self.cpu.pc.set(u16::from_le_bytes([lo, hi]));
+                    yield;
+                }
+
+                Operation::Bpl | Operation::Bmi | Operation::Bvc | Operation::Bvs
+                | Operation::Bcc | Operation::Bcs | Operation::Bne | Operation::Beq => {
+                    let taken = match instr.operation {
+                        Operation::Bpl => !self.cpu.negative(),
+                        Operation::Bmi => self.cpu.negative(),
+                        Operation::Bvc => !self.cpu.overflow(),
+                        Operation::Bvs => self.cpu.overflow(),
+                        Operation::Bcc => !self.cpu.carry(),
+                        Operation::Bcs => self.cpu.carry(),
+                        Operation::Bne => !self.cpu.zero(),
+                        Operation::Beq => self.cpu.zero(),
+                        _ => unreachable!(),
+                    };
+
+                    if taken {
+                        let offset = value.unwrap() as i8;
+                        let old_pc = self.cpu.pc.get();
+                        let new_pc = old_pc.wrapping_add(offset as u16);
+                        self.cpu.pc.set(new_pc);
+                        yield;
+
+                        if new_pc & 0xFF00 != old_pc & 0xFF00 {
+                            yield;
+                        }
+                    }
+                }
+
+                Operation::Clc => self.cpu.set_carry(false),
+                Operation::Sec => self.cpu.set_carry(true),
+                Operation::Cli => self.cpu.set_interrupt_disable(false),
+                Operation::Sei => self.cpu.set_interrupt_disable(true),
+                Operation::Clv => self.cpu.set_overflow(false),
+                Operation::Cld => self.cpu.set_decimal(false),
+                Operation::Sed => self.cpu.set_decimal(true),
+                Operation::Nop => { }
+            }
         }
-    }    
+    }
     
     fn run<'a>(&'a self) -> impl Generator<Yield = (), Return = ()> + 'a {
         let mut run_cpu = self.run_cpu();
@@ -210,85 +836,209 @@ impl Nes {
                 }
             }
 
+            self.cpu.cyc.set(self.cpu.cyc.get() + 1);
+
             // yield one cycle - both CPU and PPU have run
             yield;
         }
     }
 
     fn run_ppu<'a>(&'a self) -> impl Generator<Yield = (), Return = ()> + 'a {
+        const DOTS_PER_SCANLINE: u32 = 341;
+        const SCANLINES_PER_FRAME: u32 = 262;
+
         move || loop {
             for _frame in 0.. {
-                // - Each PPU cycle produces 1 pixel
-                // - Each line lasts 341 cycles (256 visible)
-                // - Each frame lasts 262 lines (240 visible)
-                const PPU_CYCLES_PER_FRAME: u32 = 341 * 262;
-                for cycle in 0..PPU_CYCLES_PER_FRAME {
-                    // NMI starts at the *second* cycle!
-                    if cycle == 1 {
-                        self.cpu.nmi.set(true);
-                    }
+                for raw_cycle in 0..(DOTS_PER_SCANLINE * SCANLINES_PER_FRAME) {
+                    let scanline = raw_cycle / DOTS_PER_SCANLINE;
+                    let dot = raw_cycle % DOTS_PER_SCANLINE;
 
-                    // TODO: Output pixels
+                    match scanline {
+                        0..=239 => self.render_background_dot(scanline as usize, dot),
+                        241 if dot == 1 => {
+                            self.ppu.status.set(self.ppu.status.get() | ppu::STATUS_VBLANK);
+                            self.ppu.frame_ready.set(true);
+                            if self.ppu.ctrl.get() & ppu::CTRL_NMI_ENABLE != 0 {
+                                self.cpu.nmi.set(true);
+                            }
+                        }
+                        // Pre-render line: clear VBlank one dot before
+                        // rendering of the next frame begins.
+                        261 if dot == 1 => {
+                            self.ppu.status.set(self.ppu.status.get() & !ppu::STATUS_VBLANK);
+                        }
+                        _ => {}
+                    }
 
                     yield;
                 }
             }
         }
-    }    
-}
+    }
 
-struct Rom {
-    prg_rom: Vec<u8>, // we're only doing no-mapper ROMs, so we only need PRG-ROM
-}
+    /// Run one dot's worth of background rendering for a visible
+    /// scanline: every 8th dot fetches the next tile's nametable,
+    /// attribute or pattern-table byte (in that order), the shift
+    /// registers advance every dot, and dots 1-256 each emit one pixel.
+    fn render_background_dot(&self, scanline: usize, dot: u32) {
+        if self.ppu.mask.get() & ppu::MASK_SHOW_BACKGROUND == 0 {
+            return;
+        }
 
-impl Rom {
-    fn from_file(filename: &str) -> Rom {
-        let rom_file = fs::File::open(filename).unwrap();
+        if (1..=256).contains(&dot) {
+            self.render_pixel(dot as usize - 1, scanline);
 
-        // Skip the first 10 bytes, read 16 KiB for our PRG-ROM
-        // TODO: Actually parse the ROM header!
-        let prg_rom: Vec<u8> = rom_file
-            .bytes()
-            .skip(16)
-            .take(16_384)
-            .collect::<Result<Vec<u8>, _>>()
-            .unwrap();
+            self.ppu.pattern_shift_lo.set(self.ppu.pattern_shift_lo.get() << 1);
+            self.ppu.pattern_shift_hi.set(self.ppu.pattern_shift_hi.get() << 1);
+            self.ppu.attribute_shift_lo.set(self.ppu.attribute_shift_lo.get() << 1);
+            self.ppu.attribute_shift_hi.set(self.ppu.attribute_shift_hi.get() << 1);
 
-        Rom { prg_rom }
+            match dot % 8 {
+                1 => {
+                    let addr = 0x2000 | (self.ppu.v.get() & 0x0FFF);
+                    self.ppu.next_nametable_byte.set(self.ppu_bus_read(addr));
+                }
+                3 => {
+                    let v = self.ppu.v.get();
+                    let addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    self.ppu.next_attribute_byte.set(self.ppu_bus_read(addr));
+                }
+                5 => {
+                    let addr = self.background_pattern_addr();
+                    self.ppu.next_pattern_lo.set(self.mapper.ppu_read(addr));
+                }
+                7 => {
+                    let addr = self.background_pattern_addr() + 8;
+                    self.ppu.next_pattern_hi.set(self.mapper.ppu_read(addr));
+                }
+                0 => {
+                    self.reload_background_shifters();
+                    self.increment_coarse_x();
+                }
+                _ => {}
+            }
+
+            if dot == 256 {
+                self.increment_y();
+            }
+        } else if dot == 257 {
+            // Copy the horizontal scroll bits back from `t` into `v`
+            // so the next line starts from the same column.
+            let t = self.ppu.t.get();
+            self.ppu.v.set((self.ppu.v.get() & !0x041F) | (t & 0x041F));
+        }
     }
-}
 
-#[derive(Debug)]
-struct Cpu {
-    pc: Cell<u16>,
-    a: Cell<u8>,
-    x: Cell<u8>,
-    y: Cell<u8>,
-    s: Cell<u8>,
-    p: Cell<u8>,
-    nmi: Cell<bool>,
+    /// Pattern table address of the low byte of the tile `v`'s fine Y
+    /// currently points at, in whichever half `PPUCTRL` selects.
+    fn background_pattern_addr(&self) -> u16 {
+        let fine_y = (self.ppu.v.get() >> 12) & 0x07;
+        let base: u16 = if self.ppu.ctrl.get() & ppu::CTRL_BACKGROUND_TABLE != 0 { 0x1000 } else { 0x0000 };
+        base + self.ppu.next_nametable_byte.get() as u16 * 16 + fine_y
+    }
+
+    /// Load the low byte of each shift register with the tile fetched
+    /// over the last 8 dots, ready to be shifted out over the next 8.
+    fn reload_background_shifters(&self) {
+        let v = self.ppu.v.get();
+        let coarse_x = v & 0x1F;
+        let coarse_y = (v >> 5) & 0x1F;
+        // The attribute byte packs four 2-bit palette selections, one
+        // per 2x2-tile quadrant of the 4x4-tile area it covers.
+        let shift = ((coarse_y & 0x02) << 1) | (coarse_x & 0x02);
+        let palette = (self.ppu.next_attribute_byte.get() >> shift) & 0x03;
+
+        let pattern_lo = self.ppu.pattern_shift_lo.get();
+        self.ppu.pattern_shift_lo.set((pattern_lo & 0xFF00) | self.ppu.next_pattern_lo.get() as u16);
+        let pattern_hi = self.ppu.pattern_shift_hi.get();
+        self.ppu.pattern_shift_hi.set((pattern_hi & 0xFF00) | self.ppu.next_pattern_hi.get() as u16);
+
+        let attribute_lo_fill: u16 = if palette & 0x01 != 0 { 0xFF } else { 0x00 };
+        let attribute_hi_fill: u16 = if palette & 0x02 != 0 { 0xFF } else { 0x00 };
+        let attribute_lo = self.ppu.attribute_shift_lo.get();
+        self.ppu.attribute_shift_lo.set((attribute_lo & 0xFF00) | attribute_lo_fill);
+        let attribute_hi = self.ppu.attribute_shift_hi.get();
+        self.ppu.attribute_shift_hi.set((attribute_hi & 0xFF00) | attribute_hi_fill);
+    }
+
+    /// Advance `v`'s coarse X by one tile, wrapping into the next
+    /// horizontal nametable on overflow.
+    fn increment_coarse_x(&self) {
+        let v = self.ppu.v.get();
+        if v & 0x001F == 31 {
+            self.ppu.v.set((v & !0x001F) ^ 0x0400);
+        } else {
+            self.ppu.v.set(v + 1);
+        }
+    }
+
+    /// Advance `v`'s fine Y by one scanline, carrying into coarse Y (and
+    /// from there into the next vertical nametable) as it overflows.
+    fn increment_y(&self) {
+        let v = self.ppu.v.get();
+        if v & 0x7000 != 0x7000 {
+            self.ppu.v.set(v + 0x1000);
+            return;
+        }
+
+        let mut v = v & !0x7000;
+        let mut coarse_y = (v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.ppu.v.set((v & !0x03E0) | (coarse_y << 5));
+    }
+
+    /// Combine the current bit of each shift register (selected by fine
+    /// X scroll) into a palette color and write it into the framebuffer.
+    fn render_pixel(&self, x: usize, y: usize) {
+        let select = 0x8000 >> self.ppu.x.get();
+
+        let pattern_lo = (self.ppu.pattern_shift_lo.get() & select != 0) as u8;
+        let pattern_hi = (self.ppu.pattern_shift_hi.get() & select != 0) as u8;
+        let pixel = (pattern_hi << 1) | pattern_lo;
+
+        let attribute_lo = (self.ppu.attribute_shift_lo.get() & select != 0) as u8;
+        let attribute_hi = (self.ppu.attribute_shift_hi.get() & select != 0) as u8;
+        let palette = (attribute_hi << 1) | attribute_lo;
+
+        // Every background palette's entry 0 aliases the universal
+        // background color at $3F00, regardless of which one is selected.
+        let color_addr = if pixel == 0 { 0x3F00 } else { 0x3F00 + palette as u16 * 4 + pixel as u16 };
+        let color_index = self.ppu_bus_read(color_addr) & 0x3F;
+
+        self.ppu.framebuffer.borrow_mut()[y * ppu::WIDTH + x] = ppu::NES_PALETTE[color_index as usize];
+    }
 }
 
 fn main() {
     //let rom = sample_rom();
-    let rom = Rom::from_file("tests/sample.nes");
-    let nes = Nes::from_rom(rom);
+    let rom = RomImage::from_file("tests/sample.nes");
+    // `step_frame` requires a pinned `Nes`; see the struct's doc comment.
+    let nes = Box::pin(Nes::from_rom(rom));
 
-    let mut nes_run = nes.run();
     loop {
-        match Pin::new(&mut nes_run).resume() {
-            GeneratorState::Yielded(()) => {
-                println!("> Cycle");
-            }
-            GeneratorState::Complete(_) => {
-                // stop running if our run generator stops
-                break;
-            }
-        }
+        // TODO: wire up real controller input from whatever frontend
+        // this ends up embedded in; nothing is pressed for now.
+        let input = [ControllerState::default(), ControllerState::default()];
+        let frame = nes.as_ref().step_frame(input);
+        // No real display backend yet; print the top-left pixel so
+        // there's at least some visible signal that frames are
+        // actually being rendered, not just stepped.
+        println!(
+            "> Frame ({} audio samples, top-left pixel #{:06X})",
+            frame.audio.len(),
+            frame.video[0],
+        );
     }
 }
 
-fn sample_rom() -> Rom {
+fn sample_rom() -> RomImage {
     let interrupt_vectors = vec![0x00, 0x00, 0x00, 0x80, 0x00, 0x00];
     let program = vec![
         0xA9, 0x05,
@@ -303,7 +1053,156 @@ fn sample_rom() -> Rom {
         .take(0x4000 - interrupt_vectors.len()) // ...to fill 16 KiB - 6 bytes
         .chain(interrupt_vectors)               // ...followed by interrupt vectors
         .collect();                             // ...put into a vector of bytes
-        
+
     // This is equivalent to loading our sample.nes file!
-    Rom { prg_rom }
+    RomImage {
+        mapper_number: 0,
+        mirroring: rom::Mirroring::Horizontal,
+        prg_rom,
+        chr_rom: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many more cycles a branch pays on top of `cpu::base_cycles`,
+    /// given the CPU has already been resumed through that base count.
+    ///
+    /// This can't be decided up front, before resuming: whether a branch
+    /// is taken (and crosses a page) depends on flags and `X`/`Y` that a
+    /// *preceding* load/transfer/flag instruction may still be about to
+    /// write. Those ops have no settle yield of their own (see the `Lda`
+    /// arm in `run_cpu`), so their write doesn't land until `run_cpu` is
+    /// resumed past the next instruction's opcode fetch - which, for a
+    /// branch, is exactly the first of the `base_cycles` resumes below.
+    /// Peeking flags *before* resuming those base cycles (as an earlier
+    /// version of this harness did) can read a stale flag and mispredict
+    /// `taken`, which throws off the resume count and desyncs the trace
+    /// from then on; peeking only after, as done here, reads the same
+    /// state `run_cpu`'s own branch arm is about to act on.
+    fn branch_extra(nes: &Nes, instr: cpu::Instr) -> u64 {
+        let taken = match instr.operation {
+            Operation::Bpl => !nes.cpu.negative(),
+            Operation::Bmi => nes.cpu.negative(),
+            Operation::Bvc => !nes.cpu.overflow(),
+            Operation::Bvs => nes.cpu.overflow(),
+            Operation::Bcc => !nes.cpu.carry(),
+            Operation::Bcs => nes.cpu.carry(),
+            Operation::Bne => !nes.cpu.zero(),
+            Operation::Beq => nes.cpu.zero(),
+            _ => return 0,
+        };
+
+        // `nes.cpu.pc` already sits where `run_cpu`'s own `old_pc` would
+        // - right after the branch's 2 bytes - since `base_cycles`
+        // covers the opcode and operand fetch. The operand byte itself
+        // is still sitting right before it in ROM.
+        let old_pc = nes.cpu.pc.get();
+        let offset = nes.read_u8(old_pc.wrapping_sub(1)) as i8;
+        let new_pc = old_pc.wrapping_add(offset as u16);
+        let page_crossed = taken && (new_pc & 0xFF00) != (old_pc & 0xFF00);
+
+        cpu::branch_extra_cycles(taken, page_crossed)
+    }
+
+    /// A `blargg`/`nestest`-style test-ROM harness: load a self-checking
+    /// ROM, run it from its reset vector, and diff a `trace()` line per
+    /// instruction against a golden log - the way real NES test ROMs are
+    /// normally validated, and the way this one is checked into
+    /// `tests/cpu_trace.nes` alongside its golden log at
+    /// `tests/cpu_trace.log`.
+    ///
+    /// `tests/cpu_trace.nes` exercises `SEI`, immediate `LDA`, absolute
+    /// `STA`/`STX`/`STY`-style writes, `LDX`, the stack (`PHA`/`PLA`),
+    /// `DEX`, a taken/not-taken `BNE` loop, and absolute `JMP` - enough
+    /// addressing-mode and control-flow variety to reach `base_cycles`'s
+    /// RMW, stack, and branch arms, not just its straight-line load/store
+    /// ones. It also turns on background rendering (`PPUMASK` via
+    /// `$2001`) and writes a palette byte through `$2006`/`$2007`, so a
+    /// regression in PPU register handling fails this test too, instead
+    /// of only showing up once a real ROM tries to draw something.
+    ///
+    /// The ROM reports status the same way the real `blargg` test ROMs
+    /// do: it writes $80 ("running") to $6000 and then, once done, a
+    /// final result code there (0 = pass). We stop stepping as soon as
+    /// that final write lands, or after `CYCLE_CAP` cycles if it never
+    /// does - which turns a stuck/regressed CPU into a loud, specific
+    /// test failure instead of an infinite loop.
+    ///
+    /// One quirk of diffing `trace()` this way: a load/ALU/transfer/flag
+    /// instruction's register write doesn't land until `run_cpu` is
+    /// resumed past its *own* last yield and into the next instruction's
+    /// opcode fetch (see the `Lda`/`Adc`/etc. arm above, which has no
+    /// final settle-yield of its own), so each line's `A:`/`X:`/`Y:`/`P:`
+    /// reflect the *previous* instruction's effect rather than the one
+    /// about to run at `PC:`. `tests/cpu_trace.log`'s values were derived
+    /// by hand-stepping this exact sequence against the 6502 bus-cycle
+    /// spec (opcode fetch, operand fetch, execute, one bus cycle per
+    /// `run_cpu` yield) rather than just trusting whatever this harness
+    /// printed - see `branch_extra`'s doc comment for a case that caught,
+    /// where naively trusting a first draft would have baked in a
+    /// mis-synced trace.
+    #[test]
+    fn cpu_trace_matches_golden_log_for_test_rom() {
+        const CYCLE_CAP: u64 = 10_000;
+
+        let rom = RomImage::from_file("tests/cpu_trace.nes");
+        let nes = Nes::from_rom(rom);
+        let golden = include_str!("../tests/cpu_trace.log");
+
+        let mut run = nes.run();
+        let mut lines = Vec::new();
+        let mut seen_running = false;
+        let mut cyc = 0u64;
+
+        while cyc < CYCLE_CAP {
+            let status = nes.read_u8(0x6000);
+            if status == 0x80 {
+                seen_running = true;
+            } else if seen_running {
+                break;
+            }
+
+            lines.push(nes.trace());
+
+            let opcode = nes.read_u8(nes.cpu.pc.get());
+            let instr = cpu::decode(opcode);
+
+            // Resume the guaranteed base cycles first, then (for a
+            // branch) peek the now-settled flags to see whether it's
+            // taken - see `branch_extra`'s doc comment for why the
+            // order matters.
+            for _ in 0..cpu::base_cycles(instr) {
+                match Pin::new(&mut run).resume() {
+                    GeneratorState::Yielded(()) => {}
+                    GeneratorState::Complete(()) => panic!("run generator ended early"),
+                }
+                cyc += 1;
+            }
+            for _ in 0..branch_extra(&nes, instr) {
+                match Pin::new(&mut run).resume() {
+                    GeneratorState::Yielded(()) => {}
+                    GeneratorState::Complete(()) => panic!("run generator ended early"),
+                }
+                cyc += 1;
+            }
+        }
+
+        assert!(
+            seen_running && nes.read_u8(0x6000) != 0x80,
+            "test ROM never reported completion at $6000 within {} cycles",
+            CYCLE_CAP,
+        );
+
+        let actual = lines.join("\n") + "\n";
+        assert_eq!(actual, golden, "CPU trace diverged from tests/cpu_trace.log");
+
+        assert_eq!(
+            nes.read_u8(0x6000), 0x00,
+            "test ROM reported failure status {:#04x} at $6000",
+            nes.read_u8(0x6000),
+        );
+    }
 }
@@ -0,0 +1,58 @@
+use std::cell::Cell;
+
+use super::Mapper;
+use crate::rom::RomImage;
+
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+/// Mapper 3 (CNROM): fixed PRG-ROM (16 or 32 KiB, mirrored like NROM),
+/// plus up to 4 switchable 8 KiB CHR-ROM banks selected by writing the
+/// bank number to any address in `$8000-$FFFF`.
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_bank: Cell<u8>,
+}
+
+impl Cnrom {
+    pub fn new(rom: RomImage) -> Cnrom {
+        assert!(!rom.chr_rom.is_empty(), "CNROM requires CHR-ROM");
+
+        Cnrom { prg_rom: rom.prg_rom, chr_rom: rom.chr_rom, chr_bank: Cell::new(0) }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let offset = (addr as usize - 0x8000) % self.prg_rom.len();
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&self, addr: u16, value: u8) {
+        if let 0x8000..=0xFFFF = addr {
+            // Real boards only wire up 2 bits, but we don't model bus
+            // conflicts, so just mask down to the banks we actually have.
+            let bank_count = (self.chr_rom.len() / CHR_BANK_SIZE) as u8;
+            self.chr_bank.set(value % bank_count);
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let bank_offset = self.chr_bank.get() as usize * CHR_BANK_SIZE;
+                self.chr_rom[bank_offset + addr as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    fn ppu_write(&self, _addr: u16, _value: u8) {
+        // CHR-ROM: writes are ignored.
+    }
+}
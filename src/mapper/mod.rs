@@ -0,0 +1,36 @@
+mod cnrom;
+mod nrom;
+
+use crate::rom::RomImage;
+
+pub use cnrom::Cnrom;
+pub use nrom::Nrom;
+
+/// A cartridge mapper: decodes CPU and PPU bus accesses in
+/// `$4020-$FFFF` and `$0000-$1FFF` respectively into whatever the
+/// cartridge's PRG-ROM/RAM and CHR-ROM/RAM actually contain.
+///
+/// `Nes` doesn't know or care which mapper is plugged in; it just
+/// forwards reads and writes to a `Box<dyn Mapper>`.
+///
+/// Mappers take `&self` rather than `&mut self` for writes, the same
+/// way `Nes`'s RAM is a `Cell` rather than a plain array: the CPU/PPU
+/// generators only ever hold a shared `&Nes`, so any state a mapper
+/// needs to mutate (bank registers, CHR-RAM) has to live behind
+/// interior mutability too.
+pub trait Mapper {
+    fn cpu_read(&self, addr: u16) -> u8;
+    fn cpu_write(&self, addr: u16, value: u8);
+
+    fn ppu_read(&self, addr: u16) -> u8;
+    fn ppu_write(&self, addr: u16, value: u8);
+}
+
+/// Build the right `Mapper` for a ROM image's mapper number.
+pub fn from_rom_image(rom: RomImage) -> Box<dyn Mapper> {
+    match rom.mapper_number {
+        0 => Box::new(Nrom::new(rom)),
+        3 => Box::new(Cnrom::new(rom)),
+        n => unimplemented!("mapper {} is not supported", n),
+    }
+}
@@ -0,0 +1,63 @@
+use std::cell::Cell;
+
+use super::Mapper;
+use crate::rom::RomImage;
+
+const CHR_SIZE: usize = 8 * 1024;
+
+/// Mapper 0 (NROM): no bank switching at all. PRG-ROM is either 16 KiB
+/// (mirrored to fill the 32 KiB `$8000-$FFFF` window) or 32 KiB, and
+/// CHR is a single fixed 8 KiB bank of ROM or RAM.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: Cell<[u8; CHR_SIZE]>,
+    chr_is_ram: bool,
+}
+
+impl Nrom {
+    pub fn new(rom: RomImage) -> Nrom {
+        let chr_is_ram = rom.chr_rom.is_empty();
+
+        let mut chr = [0; CHR_SIZE];
+        if !chr_is_ram {
+            chr.copy_from_slice(&rom.chr_rom);
+        }
+
+        Nrom { prg_rom: rom.prg_rom, chr: Cell::new(chr), chr_is_ram }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => {
+                let offset = (addr as usize - 0x8000) % self.prg_rom.len();
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&self, _addr: u16, _value: u8) {
+        // PRG-ROM: writes are ignored, there's nothing to bank switch.
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr: &Cell<[u8]> = &self.chr;
+                chr.as_slice_of_cells()[addr as usize].get()
+            }
+            _ => 0,
+        }
+    }
+
+    fn ppu_write(&self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            if let 0x0000..=0x1FFF = addr {
+                let chr: &Cell<[u8]> = &self.chr;
+                chr.as_slice_of_cells()[addr as usize].set(value);
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::rom::Mirroring;
+
+/// Fold a CPU-visible PPU bus address in `$2000-$3EFF` (the nametables,
+/// mirrored every 4 KiB) down to an index into the 2 KiB of physical
+/// VRAM this board actually has, according to its cartridge's wiring.
+pub fn nametable_index(addr: u16, mirroring: Mirroring) -> usize {
+    let offset = (addr - 0x2000) % 0x1000;
+    let table = offset / 0x400;
+    let cell = offset % 0x400;
+
+    let physical_table = match mirroring {
+        // Horizontal: the top two (table 0, 1) and bottom two (2, 3)
+        // logical nametables are each backed by the same physical page.
+        Mirroring::Horizontal => table / 2,
+        // Vertical: the left two (0, 2) and right two (1, 3) share a page.
+        Mirroring::Vertical => table % 2,
+    };
+
+    (physical_table * 0x400 + cell) as usize
+}
+
+/// Fold a palette address in `$3F00-$3FFF` down to an index into the 32
+/// bytes of palette RAM. Every 32 bytes repeats, and the background
+/// color of each sprite palette ($3F10/$14/$18/$1C) is itself a mirror
+/// of the corresponding background palette entry.
+pub fn palette_index(addr: u16) -> usize {
+    let index = (addr - 0x3F00) % 32;
+    if index >= 16 && index % 4 == 0 {
+        (index - 16) as usize
+    } else {
+        index as usize
+    }
+}
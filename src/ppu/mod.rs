@@ -0,0 +1,116 @@
+mod mirror;
+
+pub use mirror::{nametable_index, palette_index};
+
+use std::cell::{Cell, RefCell};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+// PPUCTRL ($2000) bits we actually look at.
+pub const CTRL_BACKGROUND_TABLE: u8 = 1 << 4;
+pub const CTRL_NMI_ENABLE: u8 = 1 << 7;
+
+// PPUMASK ($2001) bits we actually look at.
+pub const MASK_SHOW_BACKGROUND: u8 = 1 << 3;
+
+// PPUSTATUS ($2002) bits we actually look at.
+pub const STATUS_VBLANK: u8 = 1 << 7;
+
+// PPUCTRL/PPUDATA ($2007) VRAM address increment, selected by ctrl bit 2.
+pub const VRAM_INCREMENT_DOWN: u8 = 1;
+pub const VRAM_INCREMENT_ACROSS: u8 = 32;
+
+/// The 2C02's internal registers and memory, minus the cartridge's own
+/// CHR-ROM/RAM (that stays behind the `Mapper` this PPU shares with the
+/// CPU) and sprite rendering (not implemented yet).
+///
+/// As with `Cpu`, everything here is a `Cell` rather than a plain field:
+/// `run_ppu` only ever holds a shared `&Nes`. The one exception is
+/// `framebuffer`, which is a `RefCell` instead of a `Cell` so that
+/// `Nes::framebuffer()` can hand a frontend a borrowed view of it
+/// without copying 240 KiB of pixels every frame.
+pub struct Ppu {
+    pub ctrl: Cell<u8>,
+    pub mask: Cell<u8>,
+    pub status: Cell<u8>,
+
+    pub oam_addr: Cell<u8>,
+    pub oam: Cell<[u8; 256]>,
+
+    /// Current/temporary VRAM address and fine X scroll, in the usual
+    /// 2C02 `v`/`t`/`x`/`w` loopy-register scheme.
+    pub v: Cell<u16>,
+    pub t: Cell<u16>,
+    pub x: Cell<u8>,
+    pub w: Cell<bool>,
+
+    /// The byte latched by the previous $2007 read, returned instead of
+    /// the freshly-read byte for every PPUDATA read except palette ones.
+    pub read_buffer: Cell<u8>,
+
+    pub vram: Cell<[u8; 0x0800]>,
+    pub palette: Cell<[u8; 32]>,
+
+    pub framebuffer: RefCell<[u32; WIDTH * HEIGHT]>,
+
+    /// Set for one PPU cycle when VBlank begins, so that external
+    /// frame-stepping code can tell one full frame has been rendered
+    /// without disturbing `status`'s own VBlank bit (which the CPU
+    /// clears by reading $2002).
+    pub frame_ready: Cell<bool>,
+
+    // Background rendering pipeline: the tile/attribute/pattern bytes
+    // fetched for the *next* tile, and the shift registers the
+    // currently-displayed tile is drawn from.
+    pub next_nametable_byte: Cell<u8>,
+    pub next_attribute_byte: Cell<u8>,
+    pub next_pattern_lo: Cell<u8>,
+    pub next_pattern_hi: Cell<u8>,
+    pub pattern_shift_lo: Cell<u16>,
+    pub pattern_shift_hi: Cell<u16>,
+    pub attribute_shift_lo: Cell<u16>,
+    pub attribute_shift_hi: Cell<u16>,
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            ctrl: Cell::new(0),
+            mask: Cell::new(0),
+            status: Cell::new(0),
+            oam_addr: Cell::new(0),
+            oam: Cell::new([0; 256]),
+            v: Cell::new(0),
+            t: Cell::new(0),
+            x: Cell::new(0),
+            w: Cell::new(false),
+            read_buffer: Cell::new(0),
+            vram: Cell::new([0; 0x0800]),
+            palette: Cell::new([0; 32]),
+            framebuffer: RefCell::new([0; WIDTH * HEIGHT]),
+            frame_ready: Cell::new(false),
+            next_nametable_byte: Cell::new(0),
+            next_attribute_byte: Cell::new(0),
+            next_pattern_lo: Cell::new(0),
+            next_pattern_hi: Cell::new(0),
+            pattern_shift_lo: Cell::new(0),
+            pattern_shift_hi: Cell::new(0),
+            attribute_shift_lo: Cell::new(0),
+            attribute_shift_hi: Cell::new(0),
+        }
+    }
+}
+
+/// The fixed 64-color 2C02 palette, as RGB packed into the low 24 bits
+/// of each `u32`. Indexed by the 6-bit color values palette RAM holds.
+pub const NES_PALETTE: [u32; 64] = [
+    0x666666, 0x002A88, 0x1412A7, 0x3B00A4, 0x5C007E, 0x6E0040, 0x6C0600, 0x561D00,
+    0x333500, 0x0B4800, 0x005200, 0x004F08, 0x00404D, 0x000000, 0x000000, 0x000000,
+    0xADADAD, 0x155FD9, 0x4240FF, 0x7527FE, 0xA01ACC, 0xB71E7B, 0xB53120, 0x994E00,
+    0x6B6D00, 0x388700, 0x0C9300, 0x008F32, 0x007C8D, 0x000000, 0x000000, 0x000000,
+    0xFFFEFF, 0x64B0FF, 0x9290FF, 0xC676FF, 0xF36AFF, 0xFE6ECC, 0xFE8170, 0xEA9E22,
+    0xBCBE00, 0x88D800, 0x5CE430, 0x45E082, 0x48CDDE, 0x4F4F4F, 0x000000, 0x000000,
+    0xFFFEFF, 0xC0DFFF, 0xD3D2FF, 0xE8C8FF, 0xFBC2FF, 0xFEC4EA, 0xFECCC5, 0xF7D8A5,
+    0xE4E594, 0xCFEF96, 0xBDF4AB, 0xB3F3CC, 0xB5EBF2, 0xB8B8B8, 0x000000, 0x000000,
+];
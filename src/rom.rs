@@ -0,0 +1,68 @@
+use std::fs;
+use std::io::Read;
+
+/// Nametable mirroring mode, taken from bit 0 of iNES header byte 6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+}
+
+/// A parsed iNES ROM image, before it's handed off to a mapper.
+///
+/// This is intentionally dumb: it just slices the file up according to
+/// the header. Bank switching and address decoding live in `Mapper`
+/// implementations, not here.
+pub struct RomImage {
+    pub mapper_number: u8,
+    pub mirroring: Mirroring,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>, // empty means the cartridge uses CHR-RAM instead
+}
+
+const INES_MAGIC: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // "NES\x1A"
+const PRG_ROM_BANK_SIZE: usize = 16 * 1024;
+const CHR_ROM_BANK_SIZE: usize = 8 * 1024;
+const TRAINER_SIZE: usize = 512;
+
+impl RomImage {
+    pub fn from_file(filename: &str) -> RomImage {
+        let mut rom_file = fs::File::open(filename).unwrap();
+        let mut bytes = Vec::new();
+        rom_file.read_to_end(&mut bytes).unwrap();
+
+        RomImage::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> RomImage {
+        assert!(bytes.len() >= 16, "file is too small to contain an iNES header");
+        assert_eq!(&bytes[0..4], &INES_MAGIC, "missing \"NES\\x1A\" magic in iNES header");
+
+        let prg_rom_banks = bytes[4] as usize;
+        let chr_rom_banks = bytes[5] as usize;
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+
+        let has_trainer = flags6 & 0x04 != 0;
+        let mirroring = if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mapper_number = (flags6 >> 4) | (flags7 & 0xF0);
+
+        let mut offset = 16;
+        if has_trainer {
+            offset += TRAINER_SIZE;
+        }
+
+        let prg_rom_size = prg_rom_banks * PRG_ROM_BANK_SIZE;
+        let prg_rom = bytes[offset..offset + prg_rom_size].to_vec();
+        offset += prg_rom_size;
+
+        let chr_rom_size = chr_rom_banks * CHR_ROM_BANK_SIZE;
+        let chr_rom = bytes[offset..offset + chr_rom_size].to_vec();
+
+        RomImage { mapper_number, mirroring, prg_rom, chr_rom }
+    }
+}